@@ -16,7 +16,11 @@
 // relating to use of the SAFE Network Software.
 
 use lru_time_cache::LruCache;
+use std::cmp;
 use std::collections::{BTreeSet, BTreeMap};
+use std::time::{Duration, Instant};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 type Map<K,V> = BTreeMap<K,V>;
 type Set<V>   = BTreeSet<V>;
@@ -24,13 +28,89 @@ type Set<V>   = BTreeSet<V>;
 #[allow(dead_code)]
 const MAX_REQUEST_COUNT: usize = 1000;
 
+/// A strategy for deciding, from the claims accumulated so far for a request, whether the group
+/// agrees and if so on what value.
+pub trait ClaimResolver<Name, Claim>
+    where Name:  Eq + PartialOrd + Ord + Clone,
+          Claim: Eq + PartialOrd + Ord + Clone, {
+
+    fn resolve(&self, map: &Map<Name, Claim>, threshold: usize) -> Option<Claim>;
+}
+
+/// Resolves to the lower median of the accumulated claims, once at least `threshold` have been
+/// collected.
+#[allow(dead_code)]
+pub struct MedianResolver;
+
+impl<Name, Claim> ClaimResolver<Name, Claim> for MedianResolver
+    where Name:  Eq + PartialOrd + Ord + Clone,
+          Claim: Eq + PartialOrd + Ord + Clone, {
+
+    fn resolve(&self, map: &Map<Name, Claim>, threshold: usize) -> Option<Claim> {
+        if map.len() < threshold || map.is_empty() {
+            return None;
+        }
+        let mut claims = map.iter().map(|(_, ref claim)| claim.clone())
+                            .collect::<Vec<_>>();
+        claims.sort();
+        Some(claims[(claims.len() - 1) / 2].clone())
+    }
+}
+
+/// Resolves to the claim that first reaches `threshold` identical votes.
+#[allow(dead_code)]
+pub struct FrequencyResolver;
+
+impl<Name, Claim> ClaimResolver<Name, Claim> for FrequencyResolver
+    where Name:  Eq + PartialOrd + Ord + Clone,
+          Claim: Eq + PartialOrd + Ord + Clone, {
+
+    fn resolve(&self, map: &Map<Name, Claim>, threshold: usize) -> Option<Claim> {
+        let mut counts: Map<Claim, usize> = Map::new();
+        for (_, claim) in map.iter() {
+            let count = counts.entry(claim.clone()).or_insert(0);
+            *count += 1;
+            if *count >= threshold {
+                return Some(claim.clone());
+            }
+        }
+        None
+    }
+}
+
+/// Resolves only once a single claim holds a supermajority (at least ⌈2/3·n⌉ + 1) of the
+/// accumulated votes, where `n` is the number of claims seen so far.
+#[allow(dead_code)]
+pub struct SupermajorityResolver;
+
+impl<Name, Claim> ClaimResolver<Name, Claim> for SupermajorityResolver
+    where Name:  Eq + PartialOrd + Ord + Clone,
+          Claim: Eq + PartialOrd + Ord + Clone, {
+
+    fn resolve(&self, map: &Map<Name, Claim>, threshold: usize) -> Option<Claim> {
+        if map.len() < threshold {
+            return None;
+        }
+        let mut counts: Map<Claim, usize> = Map::new();
+        for (_, claim) in map.iter() {
+            *counts.entry(claim.clone()).or_insert(0) += 1;
+        }
+        let required = (2 * map.len() + 2) / 3 + 1;
+        counts.into_iter().find(|&(_, count)| count >= required).map(|(claim, _)| claim)
+    }
+}
+
 #[allow(dead_code)]
 pub struct AccountSentinel<Request, Name, Claim>
     where Request: Eq + PartialOrd + Ord + Clone,
           Name:    Eq + PartialOrd + Ord + Clone,
           Claim:   Eq + PartialOrd + Ord + Clone, {
 
-    requests: LruCache<Request, Map<Name, Claim>>,
+    requests:     LruCache<Request, Map<Name, Claim>>,
+    resolver:     Box<ClaimResolver<Name, Claim>>,
+    ttl:          Option<Duration>,
+    inserted_at:  Map<Request, Instant>,
+    max_observed: LruCache<Request, usize>,
 }
 
 impl<Request, Name, Claim> AccountSentinel<Request, Name, Claim>
@@ -40,33 +120,292 @@ impl<Request, Name, Claim> AccountSentinel<Request, Name, Claim>
 
     #[allow(dead_code)]
     pub fn new() -> AccountSentinel<Request, Name, Claim> {
+        AccountSentinel::new_with_resolver(Box::new(MedianResolver))
+    }
+
+    /// Creates an `AccountSentinel` that uses `resolver` to decide agreement, instead of the
+    /// default lower-median rule.
+    #[allow(dead_code)]
+    pub fn new_with_resolver(resolver: Box<ClaimResolver<Name, Claim>>)
+        -> AccountSentinel<Request, Name, Claim> {
+        AccountSentinel {
+            requests:     LruCache::with_capacity(MAX_REQUEST_COUNT),
+            resolver:     resolver,
+            ttl:          None,
+            inserted_at:  Map::new(),
+            max_observed: LruCache::with_capacity(MAX_REQUEST_COUNT),
+        }
+    }
+
+    /// Creates an `AccountSentinel` that evicts a request's pending claims once `ttl` has
+    /// elapsed since the first claim for it arrived, instead of waiting for `capacity` to be
+    /// exceeded. Use `poll_expired` to learn which requests timed out before reaching `threshold`.
+    #[allow(dead_code)]
+    pub fn with_expiry(capacity: usize, ttl: Duration) -> AccountSentinel<Request, Name, Claim> {
+        AccountSentinel::new_with_resolver_and_expiry(Box::new(MedianResolver), capacity, ttl)
+    }
+
+    /// Creates an `AccountSentinel` that combines a custom `resolver` with TTL-based expiry.
+    #[allow(dead_code)]
+    pub fn new_with_resolver_and_expiry(resolver: Box<ClaimResolver<Name, Claim>>, capacity: usize,
+                                         ttl: Duration) -> AccountSentinel<Request, Name, Claim> {
         AccountSentinel {
-            requests: LruCache::with_capacity(MAX_REQUEST_COUNT),
+            requests:     LruCache::with_capacity(capacity),
+            resolver:     resolver,
+            ttl:          Some(ttl),
+            inserted_at:  Map::new(),
+            max_observed: LruCache::with_capacity(capacity),
         }
     }
 
     #[allow(dead_code)]
     pub fn add_claim(&mut self, threshold: usize, request: Request, sender: Name, claim: Claim)
         -> Option<Claim> {
+        self.poll_expired();
         {
+            let is_new = !self.requests.contains_key(&request);
             let map = self.requests.entry(request.clone()).or_insert_with(||Map::new());
             map.insert(sender, claim);
-            if map.len() < threshold {
-                return None;
+            if is_new && self.ttl.is_some() {
+                self.inserted_at.insert(request.clone(), Instant::now());
             }
-            Self::pick_median(map).map(|claim|(request, claim))
+            self.resolver.resolve(map, threshold).map(|claim|(request, claim))
         }.map(|(request, claim)| {
             self.requests.remove(&request);
+            self.inserted_at.remove(&request);
             claim
         })
     }
 
-    fn pick_median(map: &Map<Name, Claim>) -> Option<Claim> {
-        if map.is_empty() { return None }
-        let mut claims = map.iter().map(|(_, ref claim)| claim.clone())
-                            .collect::<Vec<_>>();
-        claims.sort();
-        Some(claims[(claims.len() - 1) / 2].clone())
+    /// Drains and returns every request whose TTL has elapsed. A no-op unless the sentinel was
+    /// built with a TTL (`with_expiry` or `new_with_resolver_and_expiry`).
+    #[allow(dead_code)]
+    pub fn poll_expired(&mut self) -> Vec<(Request, Map<Name, Claim>)> {
+        let ttl = match self.ttl {
+            Some(ttl) => ttl,
+            None => return Vec::new(),
+        };
+
+        let now = Instant::now();
+        let expired = self.inserted_at.iter()
+                           .filter(|&(_, &inserted)| now.duration_since(inserted) >= ttl)
+                           .map(|(request, _)| request.clone())
+                           .collect::<Vec<_>>();
+
+        expired.into_iter().filter_map(|request| {
+            self.inserted_at.remove(&request);
+            self.max_observed.remove(&request);
+            self.requests.remove(&request).map(|map| (request, map))
+        }).collect()
+    }
+
+    /// Captures every request's pending claims so they can be persisted across a process
+    /// restart and handed back to `restore` afterwards. Also captures how long each request has
+    /// been accumulating (when the sentinel was built with a TTL), so `restore` can
+    /// reinstate the TTL clock instead of granting every request a fresh window.
+    #[allow(dead_code)]
+    pub fn snapshot(&self) -> Vec<PendingRequest<Request, Name, Claim>> {
+        let now = Instant::now();
+        self.requests.peek_iter().map(|(request, map)| {
+            let elapsed = self.inserted_at.get(request).map(|&inserted| now.duration_since(inserted));
+            PendingRequest(request.clone(),
+                           map.iter().map(|(n, c)| (n.clone(), c.clone())).collect(),
+                           elapsed)
+        }).collect()
+    }
+
+    /// Reloads claim sets captured by `snapshot`, re-applying the resolver against `threshold`
+    /// for each one. A request that had already reached `threshold` before the restart resolves
+    /// immediately; the rest resume accumulating where they left off, with their TTL clock
+    /// (if any) backdated by however long they had already been pending before the restart.
+    #[allow(dead_code)]
+    pub fn restore(&mut self, threshold: usize, snapshot: Vec<PendingRequest<Request, Name, Claim>>)
+        -> Vec<(Request, Claim)> {
+        let mut resolved = Vec::new();
+        let now = Instant::now();
+        for PendingRequest(request, claims, elapsed) in snapshot {
+            let map: Map<Name, Claim> = claims.into_iter().collect();
+            match self.resolver.resolve(&map, threshold) {
+                Some(claim) => resolved.push((request, claim)),
+                None => {
+                    if self.ttl.is_some() {
+                        let inserted_at = match elapsed {
+                            Some(elapsed) => now - elapsed,
+                            None => now,
+                        };
+                        self.inserted_at.insert(request.clone(), inserted_at);
+                    }
+                    self.requests.insert(request, map);
+                }
+            }
+        }
+        resolved
+    }
+
+    /// Like `add_claim`, but derives the threshold from the largest number of distinct senders
+    /// ever observed for `request`: `⌈quorum_ratio · observed_senders⌉`, floored at 2.
+    #[allow(dead_code)]
+    pub fn add_claim_quorum(&mut self, quorum_ratio: f64, request: Request, sender: Name, claim: Claim)
+        -> Option<Claim> {
+        self.poll_expired();
+
+        let is_new = !self.requests.contains_key(&request);
+        if is_new && self.ttl.is_some() {
+            self.inserted_at.insert(request.clone(), Instant::now());
+        }
+
+        let resolved = {
+            let map = self.requests.entry(request.clone()).or_insert_with(||Map::new());
+            map.insert(sender, claim);
+
+            let observed = {
+                let slot = self.max_observed.entry(request.clone()).or_insert(0);
+                if map.len() > *slot {
+                    *slot = map.len();
+                }
+                *slot
+            };
+            // A lone claim can never itself prove a quorum over an unknown population, so the
+            // fractional threshold is never allowed to fall below 2 distinct senders.
+            let threshold = cmp::max(2, (quorum_ratio * observed as f64).ceil() as usize);
+
+            self.resolver.resolve(map, threshold).map(|claim| (request, claim))
+        };
+
+        resolved.map(|(request, claim)| {
+            self.requests.remove(&request);
+            self.inserted_at.remove(&request);
+            self.max_observed.remove(&request);
+            claim
+        })
+    }
+}
+
+/// One request's pending claims, as captured by `AccountSentinel::snapshot`: the request, its
+/// accumulated `(Name, Claim)` pairs, and how long it had been pending (`None` unless the
+/// sentinel was built with a TTL).
+///
+/// Serialisable under the `serde` feature so it can be written to disk and reloaded with
+/// `AccountSentinel::restore` after a process restart.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PendingRequest<Request, Name, Claim>(pub Request, pub Vec<(Name, Claim)>, pub Option<Duration>);
+
+/// Converts a value into the canonical byte representation that gets signed over.
+pub trait ToBytes {
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+/// Like `AccountSentinel`, but only counts a sender's claim once it is backed by a signature
+/// verifying against a `PublicKey` the group itself has confirmed for that `Name`.
+#[allow(dead_code)]
+pub struct KeyedAccountSentinel<Request, Name, Claim, PublicKey, Signature>
+    where Request:   Eq + PartialOrd + Ord + Clone + ToBytes,
+          Name:      Eq + PartialOrd + Ord + Clone,
+          Claim:     Eq + PartialOrd + Ord + Clone + ToBytes,
+          PublicKey: Eq + PartialOrd + Ord + Clone, {
+
+    claims:       LruCache<Request, Map<Name, Claim>>,
+    keys:         LruCache<Name, Map<PublicKey, Set<Name>>>,
+    trusted_keys: Map<Name, PublicKey>,
+    resolver:     Box<ClaimResolver<Name, Claim>>,
+    verify_fn:    Box<Fn(&PublicKey, &[u8], &Signature) -> bool>,
+}
+
+impl<Request, Name, Claim, PublicKey, Signature>
+    KeyedAccountSentinel<Request, Name, Claim, PublicKey, Signature>
+    where Request:   Eq + PartialOrd + Ord + Clone + ToBytes,
+          Name:      Eq + PartialOrd + Ord + Clone,
+          Claim:     Eq + PartialOrd + Ord + Clone + ToBytes,
+          PublicKey: Eq + PartialOrd + Ord + Clone, {
+
+    #[allow(dead_code)]
+    pub fn new(verify_fn: Box<Fn(&PublicKey, &[u8], &Signature) -> bool>)
+        -> KeyedAccountSentinel<Request, Name, Claim, PublicKey, Signature> {
+        KeyedAccountSentinel::new_with_resolver(Box::new(MedianResolver), verify_fn)
+    }
+
+    /// Creates a `KeyedAccountSentinel` that uses `resolver` to decide agreement among verified
+    /// claims, instead of the default lower-median rule.
+    #[allow(dead_code)]
+    pub fn new_with_resolver(resolver: Box<ClaimResolver<Name, Claim>>,
+                              verify_fn: Box<Fn(&PublicKey, &[u8], &Signature) -> bool>)
+        -> KeyedAccountSentinel<Request, Name, Claim, PublicKey, Signature> {
+        KeyedAccountSentinel {
+            claims:       LruCache::with_capacity(MAX_REQUEST_COUNT),
+            keys:         LruCache::with_capacity(MAX_REQUEST_COUNT),
+            trusted_keys: Map::new(),
+            resolver:     resolver,
+            verify_fn:    verify_fn,
+        }
+    }
+
+    /// Asserts that `sender` owns `public_key`; trusted once `threshold` peers agree.
+    #[allow(dead_code)]
+    pub fn add_key_claim(&mut self, threshold: usize, sender: Name, asserter: Name, public_key: PublicKey) {
+        if self.trusted_keys.contains_key(&sender) {
+            return;
+        }
+
+        let resolved = {
+            let asserters = self.keys.entry(sender.clone())
+                                      .or_insert_with(||Map::new())
+                                      .entry(public_key.clone())
+                                      .or_insert_with(||Set::new());
+            asserters.insert(asserter);
+            asserters.len() >= threshold
+        };
+
+        if resolved {
+            self.trusted_keys.insert(sender.clone(), public_key);
+            self.keys.remove(&sender);
+        }
+    }
+
+    /// Verifies `signature` before counting `claim` toward `threshold`; returns the resolved
+    /// claim and the senders whose claims agreed with it.
+    #[allow(dead_code)]
+    pub fn add_claim(&mut self, threshold: usize, request: Request, sender: Name, claim: Claim,
+                      signature: Signature) -> Option<(Claim, BTreeSet<Name>)> {
+        let public_key = match self.trusted_keys.get(&sender) {
+            Some(public_key) => public_key.clone(),
+            None => return None,
+        };
+
+        let bytes = Self::signed_bytes(&request, &claim);
+        if !(self.verify_fn)(&public_key, &bytes, &signature) {
+            return None;
+        }
+
+        {
+            let map = self.claims.entry(request.clone()).or_insert_with(||Map::new());
+            map.insert(sender, claim);
+            if map.len() < threshold {
+                return None;
+            }
+            self.resolver.resolve(map, threshold).map(|claim| {
+                let senders = map.iter().filter(|&(_, c)| *c == claim)
+                                  .map(|(n, _)| n.clone()).collect();
+                (request, claim, senders)
+            })
+        }.map(|(request, claim, senders)| {
+            self.claims.remove(&request);
+            (claim, senders)
+        })
+    }
+
+    /// Builds the bytes signed over for `(request, claim)`. `request`'s encoding is
+    /// length-prefixed so the two components can't be re-split to produce the same bytes for a
+    /// different `(request, claim)` pair (e.g. request bytes `[1, 23]` + claim bytes `[4]` would
+    /// otherwise concatenate identically to request bytes `[1]` + claim bytes `[23, 4]`).
+    fn signed_bytes(request: &Request, claim: &Claim) -> Vec<u8> {
+        let request_bytes = request.to_bytes();
+        let claim_bytes = claim.to_bytes();
+        let mut bytes = Vec::with_capacity(8 + request_bytes.len() + claim_bytes.len());
+        bytes.extend(&(request_bytes.len() as u64).to_le_bytes());
+        bytes.extend(request_bytes);
+        bytes.extend(claim_bytes);
+        bytes
     }
 }
 
@@ -134,4 +473,324 @@ mod test {
             assert_eq!(result.unwrap(), ((threshold - 1) / 2) as Claim);
         }
     }
+
+    #[test]
+    fn expired_requests_are_drained_and_start_accumulating_fresh() {
+        use std::thread;
+
+        let ttl = Duration::from_millis(20);
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::with_expiry(10, ttl);
+        let request = 0 as Request;
+        let threshold = 3;
+
+        assert!(sentinel.add_claim(threshold, request, 0, 1).is_none());
+        assert!(sentinel.add_claim(threshold, request, 1, 2).is_none());
+
+        thread::sleep(ttl * 2);
+
+        // The stale partial claim set is handed back, not mixed into the next round.
+        let expired = sentinel.poll_expired();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, request);
+        assert_eq!(expired[0].1.len(), 2);
+
+        assert!(sentinel.add_claim(threshold, request, 2, 3).is_none());
+        assert!(sentinel.add_claim(threshold, request, 3, 4).is_none());
+        assert_eq!(sentinel.add_claim(threshold, request, 4, 5), Some(4));
+    }
+
+    #[test]
+    fn add_claim_sweeps_expired_entries_before_inserting() {
+        use std::thread;
+
+        let ttl = Duration::from_millis(20);
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::with_expiry(10, ttl);
+        let request = 0 as Request;
+        let threshold = 2;
+
+        assert!(sentinel.add_claim(threshold, request, 0, 1).is_none());
+        thread::sleep(ttl * 2);
+
+        // The sender that timed out should not count toward this fresh round.
+        assert!(sentinel.add_claim(threshold, request, 1, 2).is_none());
+        assert_eq!(sentinel.add_claim(threshold, request, 2, 3), Some(2));
+    }
+
+    #[test]
+    fn new_with_resolver_and_expiry_combines_a_custom_resolver_with_ttl() {
+        use std::thread;
+
+        let ttl = Duration::from_millis(20);
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::new_with_resolver_and_expiry(
+            Box::new(FrequencyResolver), 10, ttl);
+        let request = 0 as Request;
+        let threshold = 2;
+
+        // FrequencyResolver, not the default median, decides agreement: two identical claims
+        // resolve even though a third, dissenting claim also came in.
+        assert!(sentinel.add_claim(threshold, request, 0, 1).is_none());
+        assert!(sentinel.add_claim(threshold, request, 1, 99).is_none());
+        assert_eq!(sentinel.add_claim(threshold, request, 2, 1), Some(1));
+
+        // TTL expiry still applies, just like `with_expiry`.
+        let other_request = 1 as Request;
+        assert!(sentinel.add_claim(threshold, other_request, 0, 1).is_none());
+        thread::sleep(ttl * 2);
+        let expired = sentinel.poll_expired();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, other_request);
+    }
+
+    #[test]
+    fn snapshot_and_restore_resumes_partial_accumulation() {
+        let threshold = 3;
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::new();
+        let request = 0 as Request;
+
+        assert!(sentinel.add_claim(threshold, request, 0, 10).is_none());
+        assert!(sentinel.add_claim(threshold, request, 1, 20).is_none());
+
+        let snapshot = sentinel.snapshot();
+        assert_eq!(snapshot.len(), 1);
+
+        let mut restarted = AccountSentinel::<Request, Name, Claim>::new();
+        let resolved = restarted.restore(threshold, snapshot);
+        assert!(resolved.is_empty());
+
+        let result = restarted.add_claim(threshold, request, 2, 30);
+        assert!(result.is_some());
+        assert_eq!(result.unwrap(), 20);
+    }
+
+    #[test]
+    fn restore_backdates_the_ttl_clock_instead_of_granting_a_fresh_window() {
+        use std::thread;
+
+        let ttl = Duration::from_millis(30);
+        let threshold = 3;
+        let request = 0 as Request;
+
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::with_expiry(10, ttl);
+        assert!(sentinel.add_claim(threshold, request, 0, 10).is_none());
+
+        // The request has already used up most of its TTL before the "crash".
+        thread::sleep(ttl - Duration::from_millis(5));
+        let snapshot = sentinel.snapshot();
+
+        let mut restarted = AccountSentinel::<Request, Name, Claim>::with_expiry(10, ttl);
+        assert!(restarted.restore(threshold, snapshot).is_empty());
+
+        // A fresh TTL window would let this survive; the backdated clock means it is already
+        // expired moments after the restart.
+        thread::sleep(Duration::from_millis(10));
+        let expired = restarted.poll_expired();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, request);
+    }
+
+    #[test]
+    fn restore_resolves_requests_that_already_met_threshold() {
+        let threshold = 2;
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::new();
+        let request = 0 as Request;
+
+        // Snapshot a request that never got a chance to resolve before the crash, even though
+        // it had already reached `threshold` distinct claims.
+        let snapshot = vec![PendingRequest(request, vec![(0 as Name, 10 as Claim), (1 as Name, 20 as Claim)], None)];
+
+        let resolved = sentinel.restore(threshold, snapshot);
+        assert_eq!(resolved, vec![(request, 10 as Claim)]);
+    }
+
+    #[test]
+    fn quorum_threshold_requires_at_least_two_distinct_senders() {
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::new();
+        let request = 0 as Request;
+
+        // A lone claim can never prove a quorum by itself, however generous the ratio.
+        assert!(sentinel.add_claim_quorum(0.9, request, 0, 1).is_none());
+        assert_eq!(sentinel.add_claim_quorum(0.9, request, 1, 2), Some(1));
+    }
+
+    #[test]
+    fn quorum_threshold_scales_with_the_observed_group_as_it_grows() {
+        // The caller never has to pass an absolute threshold sized to the group: it falls out
+        // of however many distinct senders actually show up, and (via the resolver) still
+        // requires genuine agreement rather than just a headcount.
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::new_with_resolver(
+            Box::new(FrequencyResolver));
+        let request = 0 as Request;
+        let ratio = 0.75;
+
+        assert!(sentinel.add_claim_quorum(ratio, request, 0, 111).is_none());
+        assert!(sentinel.add_claim_quorum(ratio, request, 1, 222).is_none());
+        // Observed group is now 3, needing ⌈0.75·3⌉ = 3 votes for one claim; 111 only has 2.
+        assert!(sentinel.add_claim_quorum(ratio, request, 2, 111).is_none());
+        assert_eq!(sentinel.add_claim_quorum(ratio, request, 3, 111), Some(111));
+    }
+
+    #[test]
+    fn frequency_resolver_waits_for_identical_votes() {
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::new_with_resolver(
+            Box::new(FrequencyResolver));
+        let request = 0 as Request;
+        let threshold = 3;
+
+        assert!(sentinel.add_claim(threshold, request, 0, 111).is_none());
+        assert!(sentinel.add_claim(threshold, request, 1, 222).is_none());
+        // A third distinct claim resolves nothing, and a second vote for 111 is still one short.
+        assert!(sentinel.add_claim(threshold, request, 2, 333).is_none());
+        assert_eq!(sentinel.add_claim(threshold, request, 3, 111), None);
+        assert_eq!(sentinel.add_claim(threshold, request, 4, 111), Some(111));
+    }
+
+    #[test]
+    fn supermajority_resolver_tolerates_a_lone_dissenter() {
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::new_with_resolver(
+            Box::new(SupermajorityResolver));
+        let request = 0 as Request;
+        let threshold = 6;
+
+        // 5 of 6 senders agree on 1; ⌈2/3·6⌉ + 1 = 5, so the lone dissenter is tolerated.
+        for sender in 0..4 {
+            assert!(sentinel.add_claim(threshold, request, sender, 1).is_none());
+        }
+        assert!(sentinel.add_claim(threshold, request, 4, 2).is_none());
+        assert_eq!(sentinel.add_claim(threshold, request, 5, 1), Some(1));
+    }
+
+    #[test]
+    fn supermajority_resolver_returns_none_when_group_is_split() {
+        let mut sentinel = AccountSentinel::<Request, Name, Claim>::new_with_resolver(
+            Box::new(SupermajorityResolver));
+        let request = 0 as Request;
+        let threshold = 6;
+
+        // An even 3-3 split never reaches ⌈2/3·6⌉ + 1 = 5 votes for either claim.
+        for sender in 0..3 {
+            assert!(sentinel.add_claim(threshold, request, sender, 1).is_none());
+        }
+        for sender in 3..5 {
+            assert!(sentinel.add_claim(threshold, request, sender, 2).is_none());
+        }
+        assert_eq!(sentinel.add_claim(threshold, request, 5, 2), None);
+    }
+
+    type PublicKey = u64;
+    type Signature = u64;
+
+    impl ToBytes for Request {
+        fn to_bytes(&self) -> Vec<u8> { vec![*self] }
+    }
+
+    impl ToBytes for Claim {
+        fn to_bytes(&self) -> Vec<u8> { vec![*self as u8] }
+    }
+
+    fn accepting_verify_fn() -> Box<Fn(&PublicKey, &[u8], &Signature) -> bool> {
+        Box::new(|public_key: &PublicKey, bytes: &[u8], signature: &Signature| {
+            *signature == *public_key + bytes.len() as u64
+        })
+    }
+
+    #[test]
+    fn keyed_rejects_claim_from_unconfirmed_key() {
+        let mut sentinel = KeyedAccountSentinel::<Request, Name, Claim, PublicKey, Signature>::new(
+            accepting_verify_fn());
+
+        // No key has been confirmed for `sender` yet, so the claim is dropped outright.
+        assert!(sentinel.add_claim(1, 0 as Request, 0 as Name, 42 as Claim, 0).is_none());
+    }
+
+    #[test]
+    fn keyed_resolves_once_key_and_claims_are_confirmed() {
+        let threshold = 2;
+        let mut sentinel = KeyedAccountSentinel::<Request, Name, Claim, PublicKey, Signature>::new(
+            accepting_verify_fn());
+
+        let request = 0 as Request;
+        let claim = 42 as Claim;
+        let bytes_len = KeyedAccountSentinel::<Request, Name, Claim, PublicKey, Signature>
+            ::signed_bytes(&request, &claim).len();
+
+        // Two senders, each with a key confirmed by two asserters, submit the same claim.
+        for sender in 0..2 {
+            let public_key = (10 + sender) as PublicKey;
+            sentinel.add_key_claim(threshold, sender as Name, 100 as Name, public_key);
+            sentinel.add_key_claim(threshold, sender as Name, 101 as Name, public_key);
+
+            let signature = public_key + bytes_len as u64;
+            let result = sentinel.add_claim(threshold, request, sender as Name, claim, signature);
+            if sender == 0 {
+                assert!(result.is_none());
+            } else {
+                let (resolved_claim, senders) = result.unwrap();
+                assert_eq!(resolved_claim, claim);
+                assert_eq!(senders, vec![0 as Name, 1 as Name].into_iter().collect());
+            }
+        }
+    }
+
+    #[test]
+    fn keyed_uses_the_injected_resolver_instead_of_the_default_median() {
+        let threshold = 2;
+        let mut sentinel = KeyedAccountSentinel::<Request, Name, Claim, PublicKey, Signature>
+            ::new_with_resolver(Box::new(FrequencyResolver), accepting_verify_fn());
+
+        let request = 0 as Request;
+        let agreeing_claim = 1 as Claim;
+        let dissenting_claim = 99 as Claim;
+
+        let rounds = vec![(0, agreeing_claim), (1, dissenting_claim), (2, agreeing_claim)];
+        for (sender, claim) in rounds {
+            let public_key = (10 + sender) as PublicKey;
+            sentinel.add_key_claim(threshold, sender as Name, 100 as Name, public_key);
+            sentinel.add_key_claim(threshold, sender as Name, 101 as Name, public_key);
+
+            let bytes_len = KeyedAccountSentinel::<Request, Name, Claim, PublicKey, Signature>
+                ::signed_bytes(&request, &claim).len();
+            let signature = public_key + bytes_len as u64;
+            let result = sentinel.add_claim(threshold, request, sender as Name, claim, signature);
+
+            // A lower-median resolver would have resolved on the second (dissenting) claim
+            // already; FrequencyResolver instead waits for a second identical vote.
+            if sender == 2 {
+                let (resolved_claim, senders) = result.unwrap();
+                assert_eq!(resolved_claim, agreeing_claim);
+                // The dissenter (sender 1) must not be credited as part of the resolving set.
+                assert_eq!(senders, vec![0 as Name, 2 as Name].into_iter().collect());
+            } else {
+                assert!(result.is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn signed_bytes_are_not_ambiguous_across_different_splits() {
+        // Without a length prefix, request bytes `[1, 23]` + claim bytes `[4]` would concatenate
+        // identically to request bytes `[1]` + claim bytes `[23, 4]`; the length prefix on the
+        // request component must keep these distinct.
+        type Sentinel = KeyedAccountSentinel<Vec<u8>, Name, Vec<u8>, PublicKey, Signature>;
+
+        impl ToBytes for Vec<u8> {
+            fn to_bytes(&self) -> Vec<u8> { self.clone() }
+        }
+
+        let a = Sentinel::signed_bytes(&vec![1, 23], &vec![4]);
+        let b = Sentinel::signed_bytes(&vec![1], &vec![23, 4]);
+        assert!(a != b);
+    }
+
+    #[test]
+    fn keyed_rejects_bad_signature() {
+        let threshold = 1;
+        let mut sentinel = KeyedAccountSentinel::<Request, Name, Claim, PublicKey, Signature>::new(
+            accepting_verify_fn());
+
+        let sender = 0 as Name;
+        let public_key = 7 as PublicKey;
+        sentinel.add_key_claim(threshold, sender, 1 as Name, public_key);
+
+        assert!(sentinel.add_claim(threshold, 0 as Request, sender, 42 as Claim, 0).is_none());
+    }
 }